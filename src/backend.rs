@@ -0,0 +1,194 @@
+use std::env;
+use std::fmt;
+use std::io;
+use std::process::{Command, ExitStatus};
+use std::str::from_utf8;
+
+/// The result of running a backend command: its exit status plus captured output.
+pub struct CommandOutput {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// POSIX-ish error categories for a failed backend invocation, so callers can report
+/// something more useful than "it didn't work" and `main` can pick a sensible exit code.
+#[derive(Debug)]
+pub enum BackendError {
+    /// The backend executable itself couldn't be found (e.g. `git` not on PATH).
+    NotFound,
+    /// The backend executable exists but couldn't be run, or a config write was
+    /// refused, due to filesystem permissions (e.g. a read-only or locked repo).
+    PermissionDenied,
+    /// The backend rejected the arguments it was given.
+    InvalidArgument(String),
+    /// The command ran and exited non-zero for some other reason.
+    CommandFailed { stderr: String },
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BackendError::NotFound => write!(f, "backend command not found"),
+            BackendError::PermissionDenied => write!(f, "permission denied"),
+            BackendError::InvalidArgument(detail) => write!(f, "invalid argument: {}", detail.trim()),
+            BackendError::CommandFailed { stderr } if stderr.trim().is_empty() => write!(f, "command failed"),
+            BackendError::CommandFailed { stderr } => write!(f, "command failed: {}", stderr.trim()),
+        }
+    }
+}
+
+impl std::error::Error for BackendError {}
+
+impl BackendError {
+    /// A POSIX-flavoured process exit code for this error category.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            BackendError::NotFound => 127,
+            BackendError::PermissionDenied => 13,
+            BackendError::InvalidArgument(_) => 22,
+            BackendError::CommandFailed { .. } => 1,
+        }
+    }
+
+    fn from_spawn_error(e: io::Error) -> BackendError {
+        match e.kind() {
+            io::ErrorKind::NotFound => BackendError::NotFound,
+            io::ErrorKind::PermissionDenied => BackendError::PermissionDenied,
+            _ => BackendError::CommandFailed { stderr: e.to_string() },
+        }
+    }
+
+    fn from_failed_output(stderr: &str) -> BackendError {
+        let lower = stderr.to_lowercase();
+        if lower.contains("permission denied") {
+            BackendError::PermissionDenied
+        } else if lower.contains("invalid") {
+            BackendError::InvalidArgument(stderr.to_owned())
+        } else {
+            BackendError::CommandFailed { stderr: stderr.to_owned() }
+        }
+    }
+}
+
+/// A version-control backend that can read and write the "current identity" used
+/// for commits in a working copy. `git-profile` ships a `GitBackend`; third
+/// parties can implement this trait to add Mercurial, Fossil, jj, etc. support
+/// without touching the core profile-switching logic.
+pub trait Backend {
+    /// Sets the author name/email that new commits in the working copy will use.
+    fn set_identity(&self, author: &str, email: &str) -> Result<(), BackendError>;
+
+    /// Sets an arbitrary config key (e.g. `user.signingkey`, `commit.gpgsign`,
+    /// `core.sshCommand`), for profiles that encapsulate more than the author/email.
+    fn set_config(&self, key: &str, value: &str) -> Result<(), BackendError>;
+
+    /// Returns the email currently configured for commits, or `None` if nothing's set.
+    fn current_email(&self) -> Result<Option<String>, BackendError>;
+
+    /// Returns whether the current directory (or an ancestor) is a working copy
+    /// for this backend.
+    fn is_repo(&self) -> bool;
+
+    /// Clones `url` into a new working copy in the current directory.
+    fn clone_repo(&self, url: &str) -> Result<(), BackendError>;
+}
+
+/// The default backend: shells out to `git`.
+pub struct GitBackend;
+
+impl Backend for GitBackend {
+    fn set_identity(&self, author: &str, email: &str) -> Result<(), BackendError> {
+        run_checked(vec!["config", "user.name", author])?;
+        run_checked(vec!["config", "user.email", email])?;
+        Ok(())
+    }
+
+    fn set_config(&self, key: &str, value: &str) -> Result<(), BackendError> {
+        run_checked(vec!["config", key, value])
+    }
+
+    fn current_email(&self) -> Result<Option<String>, BackendError> {
+        let output = git_command(vec!["config", "user.email"])?;
+
+        // `git config` exits non-zero when the key just isn't set, which isn't an
+        // error from our point of view - there's simply no local identity yet.
+        if !output.status.success() || output.stdout.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(output.stdout))
+    }
+
+    fn is_repo(&self) -> bool {
+        find_ancestor_with(".git")
+    }
+
+    fn clone_repo(&self, url: &str) -> Result<(), BackendError> {
+        run_checked(vec!["clone", url])
+    }
+}
+
+/// Walks up from the current directory looking for `marker` (e.g. `.git`), the same way
+/// `git` itself resolves the repository root when run from a subdirectory.
+fn find_ancestor_with(marker: &str) -> bool {
+    let mut dir = match env::current_dir() {
+        Ok(dir) => dir,
+        Err(_) => return false,
+    };
+
+    loop {
+        if dir.join(marker).exists() {
+            return true;
+        }
+
+        if !dir.pop() {
+            return false;
+        }
+    }
+}
+
+/// Picks the backend for the current directory by auto-detecting the working-copy
+/// marker each known backend leaves behind (`.git`, `.hg`, ...).
+///
+/// TODO: Allow picking a backend via config once a second backend actually exists;
+/// auto-detection is all that's needed while `git` is the only implementation.
+pub fn detect_backend() -> Box<dyn Backend> {
+    let candidates: Vec<Box<dyn Backend>> = vec![Box::new(GitBackend)];
+
+    for backend in candidates {
+        if backend.is_repo() {
+            return backend;
+        }
+    }
+
+    Box::new(GitBackend)
+}
+
+/// Runs a `git` command (`config` write, `clone`, ...) and treats any non-success exit
+/// status as an error, regardless of whether `git` printed anything to stderr.
+/// Reads (like `config --get`, where a non-zero exit just means "unset") should call
+/// `git_command` directly instead and interpret the status themselves.
+fn run_checked(args: Vec<&str>) -> Result<(), BackendError> {
+    let output = git_command(args)?;
+
+    if !output.status.success() {
+        return Err(BackendError::from_failed_output(&output.stderr));
+    }
+
+    Ok(())
+}
+
+fn git_command(args: Vec<&str>) -> Result<CommandOutput, BackendError> {
+    let mut command = Command::new("git");
+
+    for arg in args {
+        command.arg(arg);
+    }
+
+    let output_streams = command.output().map_err(BackendError::from_spawn_error)?;
+    let stdout = from_utf8(&output_streams.stdout).unwrap_or("").trim_end().to_owned();
+    let stderr = from_utf8(&output_streams.stderr).unwrap_or("").trim_end().to_owned();
+
+    Ok(CommandOutput { status: output_streams.status, stdout, stderr })
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+/// Scores a fuzzy subsequence match between `query` and `candidate`.
+///
+/// Returns `None` if `query`'s characters don't all appear in `candidate`, in
+/// order (case-insensitive). Otherwise returns a score where higher is a
+/// better match: contiguous runs of matched characters, matches at a word
+/// boundary (start of string, or right after `_`/`-`/` `), and matches that
+/// occur earlier in the candidate are all rewarded; large gaps between
+/// consecutive matched characters are penalised.
+///
+/// Among all valid subsequence alignments of `query` onto `candidate`, the
+/// highest-scoring one is used - not just the first (leftmost) one found, so
+/// a later, boundary-aligned match can beat an earlier one that breaks a run.
+pub fn score(query: &str, candidate: &str) -> Option<i64> {
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let candidate_chars: Vec<char> = candidate.chars().collect();
+    let candidate_lower: Vec<char> = candidate.to_lowercase().chars().collect();
+    let query_lower: Vec<char> = query.to_lowercase().chars().collect();
+
+    let mut memo = HashMap::new();
+    best_alignment(&query_lower, &candidate_chars, &candidate_lower, 0, None, 0, &mut memo)
+}
+
+/// The best achievable score for matching `query[qi..]` onto `candidate[search_from..]`,
+/// given that the previous matched character (if any) was at `last_idx` and ended a run
+/// of length `run_length`. Memoized on `(qi, last_idx, run_length)`, since that's all the
+/// state later choices depend on.
+fn best_alignment(
+    query: &[char],
+    candidate_chars: &[char],
+    candidate_lower: &[char],
+    qi: usize,
+    last_idx: Option<usize>,
+    run_length: i64,
+    memo: &mut HashMap<(usize, Option<usize>, i64), Option<i64>>,
+) -> Option<i64> {
+    if qi == query.len() {
+        return Some(0);
+    }
+
+    let key = (qi, last_idx, run_length);
+    if let Some(&cached) = memo.get(&key) {
+        return cached;
+    }
+
+    let search_from = last_idx.map_or(0, |i| i + 1);
+    let q = query[qi];
+
+    let mut best: Option<i64> = None;
+
+    for idx in search_from..candidate_lower.len() {
+        if candidate_lower[idx] != q {
+            continue;
+        }
+
+        let is_boundary = idx == 0 || matches!(candidate_chars[idx - 1], '_' | '-' | ' ');
+        let is_contiguous = last_idx.is_some_and(|last| idx == last + 1);
+        let new_run_length = if is_contiguous { run_length + 1 } else { 1 };
+
+        let mut char_score: i64 = 10 + (new_run_length * 5) - idx as i64;
+        if is_boundary {
+            char_score += 15;
+        }
+
+        if let Some(last) = last_idx {
+            let gap = idx as i64 - last as i64 - 1;
+            char_score -= gap * 2;
+        }
+
+        if let Some(rest) = best_alignment(query, candidate_chars, candidate_lower, qi + 1, Some(idx), new_run_length, memo) {
+            let total = char_score + rest;
+            best = Some(best.map_or(total, |b| b.max(total)));
+        }
+    }
+
+    memo.insert(key, best);
+    best
+}
+
+/// Ranks `candidates` against `query`, keeping only subsequence matches and
+/// sorting by descending score (best match first). Non-matches are dropped.
+pub fn rank<'a>(query: &str, candidates: &[&'a str]) -> Vec<&'a str> {
+    let mut scored: Vec<(i64, &'a str)> = candidates.iter()
+        .filter_map(|&c| score(query, c).map(|s| (s, c)))
+        .collect();
+
+    scored.sort_by_key(|&(s, _)| std::cmp::Reverse(s));
+    scored.into_iter().map(|(_, c)| c).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_query_matches_everything_with_zero_score() {
+        assert_eq!(score("", "anything"), Some(0));
+    }
+
+    #[test]
+    fn rejects_non_subsequences() {
+        assert_eq!(score("xyz", "abc"), None);
+        assert_eq!(score("ba", "ab"), None);
+    }
+
+    #[test]
+    fn prefers_the_best_alignment_over_the_leftmost_one() {
+        // Binding "a" to its first occurrence (index 1) forces "b" onto a lone,
+        // non-contiguous, non-boundary match at index 4. Binding "a" to the later
+        // occurrence at index 3 (right after "_", a boundary) lets "b" match
+        // contiguously at index 4 instead - a strictly better alignment that a
+        // leftmost-only scan would never consider.
+        assert_eq!(score("ab", "xa_ab"), Some(43));
+    }
+
+    #[test]
+    fn rewards_contiguous_runs() {
+        let contiguous = score("ab", "ab").unwrap();
+        let gapped = score("ab", "axb").unwrap();
+        assert!(contiguous > gapped);
+    }
+
+    #[test]
+    fn rewards_word_boundary_matches() {
+        let boundary = score("b", "a_b").unwrap();
+        let mid_word = score("b", "abc").unwrap();
+        assert!(boundary > mid_word);
+    }
+
+    #[test]
+    fn rewards_earlier_matches() {
+        let earlier = score("a", "abc").unwrap();
+        let later = score("a", "xabc").unwrap();
+        assert!(earlier > later);
+    }
+
+    #[test]
+    fn rank_orders_best_match_first() {
+        let candidates = ["xa_ab", "ab", "back_ab"];
+        let ranked = rank("ab", &candidates);
+        assert_eq!(ranked[0], "ab");
+    }
+}
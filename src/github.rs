@@ -0,0 +1,49 @@
+extern crate octocrab;
+extern crate serde_derive;
+extern crate tokio;
+
+use octocrab::Octocrab;
+use serde_derive::Deserialize;
+
+/// Identity details resolved from a GitHub account, used to pre-fill a new
+/// profile when `git-profile new` is given `--from-github`.
+pub struct GithubIdentity {
+    pub author: Option<String>,
+    pub email: Option<String>,
+    pub username: String,
+    pub url: Option<String>,
+}
+
+/// The subset of the `GET /users/{username}` response we care about. Octocrab's
+/// own `models::User` is the minimal shape returned when a user appears nested
+/// inside another resource, and doesn't carry `name`/`email` - we need the full
+/// profile response, so we deserialize it into our own struct instead.
+#[derive(Deserialize)]
+struct GithubUser {
+    name: Option<String>,
+    email: Option<String>,
+}
+
+/// Fetches public profile details for `handle` from the GitHub API.
+///
+/// `octocrab` is async; the rest of the application is still synchronous, so this
+/// spins up a throwaway Tokio runtime and blocks on it rather than pushing async
+/// down through `main`.
+pub fn fetch_identity(handle: &str) -> Result<GithubIdentity, Box<dyn std::error::Error>> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(fetch_identity_async(handle))
+}
+
+async fn fetch_identity_async(handle: &str) -> Result<GithubIdentity, Box<dyn std::error::Error>> {
+    let octocrab = Octocrab::builder().build()?;
+    let user: GithubUser = octocrab.get(format!("users/{}", handle), None::<&()>).await?;
+
+    // GitHub will omit `email` entirely when the account keeps it private; callers
+    // fall back to the manually supplied EMAIL arg in that case.
+    Ok(GithubIdentity {
+        author: user.name,
+        email: user.email,
+        username: handle.to_owned(),
+        url: Some(format!("git@github.com:{}/{{{{project}}}}", handle)),
+    })
+}
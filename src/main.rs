@@ -3,14 +3,21 @@ extern crate dirs;
 extern crate serde_derive;
 extern crate ramhorns;
 
+mod github;
+mod fuzzy;
+mod picker;
+mod backend;
+
+use backend::Backend;
+
 use std::collections::HashMap;
 use std::env;
 use std::error::Error;
 use std::fs;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
+use std::process;
 use std::process::Command;
-use std::str::from_utf8;
 use std::vec::Vec;
 
 use clap::{App, Arg, SubCommand};
@@ -26,6 +33,9 @@ struct Profile {
     email: String,
     username: Option<String>,
     url: Option<String>,
+    /// Arbitrary extra git config keys to apply alongside user.name/user.email, e.g.
+    /// `user.signingkey`, `commit.gpgsign`, `core.sshCommand`.
+    config: Option<HashMap<String, String>>,
 }
 
 #[derive(Content)]
@@ -43,6 +53,7 @@ impl Profile {
             email: author_email.to_owned(),
             username: None,
             url: None,
+            config: None,
         }
     }
 
@@ -56,18 +67,31 @@ impl Profile {
         self
     }
 
-    fn as_map(&self) -> HashMap<String, String> {
+    fn with_config_value(&mut self, key: &str, value: &str) -> &mut Profile {
+        self.config.get_or_insert_with(HashMap::new).insert(key.to_owned(), value.to_owned());
+        self
+    }
+
+    fn as_map(&self) -> HashMap<String, Value> {
         let mut map = HashMap::new();
 
-        map.insert("author".to_owned(), self.author.clone());
-        map.insert("email".to_owned(), self.email.clone());
+        map.insert("author".to_owned(), Value::String(self.author.clone()));
+        map.insert("email".to_owned(), Value::String(self.email.clone()));
 
         if let Some(username) = &self.username {
-            map.insert("username".to_owned(), username.clone());
+            map.insert("username".to_owned(), Value::String(username.clone()));
         }
 
         if let Some(url) = &self.url {
-            map.insert("url".to_owned(), url.clone());
+            map.insert("url".to_owned(), Value::String(url.clone()));
+        }
+
+        if let Some(config) = &self.config {
+            let mut table = toml::value::Table::new();
+            for (key, value) in config {
+                table.insert(key.clone(), Value::String(value.clone()));
+            }
+            map.insert("config".to_owned(), Value::Table(table));
         }
 
         map
@@ -80,8 +104,8 @@ impl Profile {
         };
 
         UrlRenderData{
-            project: project,
-            username: username
+            project,
+            username
         }
     }
 
@@ -90,12 +114,13 @@ impl Profile {
 struct GitProfilesApp<'a> {
     profiles: Option<Vec<Profile>>,
     args: Option<clap::ArgMatches<'a>>,
+    backend: Box<dyn Backend>,
 }
 
 impl GitProfilesApp<'_> {
 
     fn new<'a>() -> Result<GitProfilesApp<'a>, std::io::Error> {
-        let mut app = GitProfilesApp{ profiles: None, args: None };
+        let mut app = GitProfilesApp{ profiles: None, args: None, backend: backend::detect_backend() };
         app.parse_args();
 
         let profiles = app.load_profiles()?;
@@ -115,9 +140,9 @@ impl GitProfilesApp<'_> {
                                 .help("Name of profile to create")
                                 .required(true))
                         .arg(Arg::with_name("AUTHOR")
-                                .required(true))
+                                .required_unless("FROM_GITHUB"))
                         .arg(Arg::with_name("EMAIL")
-                                .required(true))
+                                .required_unless("FROM_GITHUB"))
                         .arg(Arg::with_name("USERNAME")
                                 .short("u")
                                 .long("username")
@@ -126,6 +151,17 @@ impl GitProfilesApp<'_> {
                                 .short("r")
                                 .long("remote")
                                 .takes_value(true))
+                        .arg(Arg::with_name("FROM_GITHUB")
+                                .short("g")
+                                .long("from-github")
+                                .takes_value(true)
+                                .help("Populate author/email/username/url from a GitHub handle"))
+                        .arg(Arg::with_name("SET")
+                                .long("set")
+                                .takes_value(true)
+                                .number_of_values(1)
+                                .multiple(true)
+                                .help("Extra git config key=value to apply with this profile, e.g. --set user.signingkey=ABCD1234"))
                         // TODO: Add --edit arg, opens file in editor _after_ writing new profile data
                 )
                 .subcommand(
@@ -136,11 +172,17 @@ impl GitProfilesApp<'_> {
                     SubCommand::with_name("use")
                         .about("Switch profile")
                         .arg(Arg::with_name("PROFILE")
-                                .help("Profile to operate on")
-                                .required(true)
+                                .help("Profile to switch to, or a partial name to narrow an interactive picker")
                                 .takes_value(true))
                         // TODO: Add --global flag, operating on git config --global
                 )
+                .subcommand(
+                    SubCommand::with_name("shell")
+                        .about("Spawn a subshell with a profile's identity set via git's GIT_AUTHOR_*/GIT_COMMITTER_* env vars")
+                        .arg(Arg::with_name("PROFILE")
+                                .help("Profile to use, or a partial name to narrow an interactive picker")
+                                .takes_value(true))
+                )
                 .subcommand(
                     SubCommand::with_name("url")
                         .about("Generate remote url")
@@ -153,6 +195,18 @@ impl GitProfilesApp<'_> {
                                 .takes_value(true)
                                 .help("Profile to use"))
                 )
+                .subcommand(
+                    SubCommand::with_name("clone")
+                        .about("Clone a repo and apply a profile's identity to the fresh checkout")
+                        .arg(Arg::with_name("TARGET")
+                                .help("Clone URL, or a bare project name to expand via the profile's url template")
+                                .required(true))
+                        .arg(Arg::with_name("PROFILE")
+                                .short("p")
+                                .long("profile")
+                                .takes_value(true)
+                                .help("Profile to use"))
+                )
                 .subcommand(
                     SubCommand::with_name("author")
                         .about("Get profile's author string in git format")
@@ -252,13 +306,14 @@ impl GitProfilesApp<'_> {
     }
 
     fn get_profile_in_local_use(&self) -> Option<&Profile> {
-        // TODO: Need to handle the case this is run in a non-git dir. Manually detect ./.git dir?
-        let email = git_command(vec!["config", "user.email"]);
-        if let Some(profile) = self.get_profile_by_email(email) {
-            return Some(profile);
+        if !self.backend.is_repo() {
+            return None;
         }
 
-        None
+        // A failed lookup here (git missing, odd filesystem state, ...) just means we can't
+        // work out a local profile - not worth surfacing as an error for a read-only check.
+        let email = self.backend.current_email().ok().flatten()?;
+        self.get_profile_by_email(email)
     }
 
     fn get_default_profile(&self) -> Option<&Profile> {
@@ -267,7 +322,7 @@ impl GitProfilesApp<'_> {
         }
 
         if let Some(profiles) = &self.profiles {
-            if profiles.len() > 0 {
+            if !profiles.is_empty() {
                 return Some(&self.profiles.as_ref().unwrap()[0]);
             }
         }
@@ -275,17 +330,37 @@ impl GitProfilesApp<'_> {
         None
     }
 
-    /// Unwraps the profile name, finds a matching profile (or falls back to a reasonable default) then executes the 
-    /// closure with the profile as it's argument.
-    fn with_profile<F>(&self, name: Option<&str>, f: F) 
-        where F: Fn(&Profile) -> ()
-    {
-        let profile_opt = match name {
+    /// Opens an interactive fuzzy picker over all known profile names, seeded with
+    /// `initial_query`, and resolves the user's selection back to a `Profile`.
+    fn pick_profile_interactively(&self, initial_query: &str) -> Option<&Profile> {
+        let profiles = self.profiles.as_ref()?;
+        let names: Vec<&str> = profiles.iter().map(|p| p.name.as_str()).collect();
+
+        match picker::pick(&names, initial_query) {
+            Ok(Some(name)) => self.get_profile(name),
+            Ok(None) => None,
+            Err(e) => {
+                println!("Interactive picker failed: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Finds a matching profile for `name` (or falls back to a reasonable default when no name
+    /// is given), falling back further to an interactive fuzzy picker over all profiles.
+    fn resolve_profile(&self, name: Option<&str>) -> Option<&Profile> {
+        match name {
             Some(name) => self.get_profile(name.to_owned()),
-            None => self.get_default_profile()
-        };
+            None => self.get_default_profile().or_else(|| self.pick_profile_interactively(""))
+        }
+    }
 
-        match profile_opt {
+    /// Resolves the profile named `name` (see `resolve_profile`) then executes the closure
+    /// with the profile as its argument.
+    fn with_profile<F>(&self, name: Option<&str>, f: F)
+        where F: Fn(&Profile)
+    {
+        match self.resolve_profile(name) {
             None => {
                 println!("Couldn't find specified profile, or work out a default");
             },
@@ -299,7 +374,7 @@ impl GitProfilesApp<'_> {
         let no_profiles = || println!("No profiles defined");
 
         if let Some(profiles) = &self.profiles {
-            if profiles.len() == 0 {
+            if profiles.is_empty() {
                 no_profiles();
                 return
             }
@@ -316,21 +391,87 @@ impl GitProfilesApp<'_> {
                     }
                 }
 
-                print!("\n");  // TODO: Does this work cross-platform?
+                println!();  // TODO: Does this work cross-platform?
             }
         } else {
             no_profiles();
         }
     }
 
-    fn handle_use(&self, target: String) {
-        // We never want to fallback to a default when dealing with 'use' cmd, so we don't use `with_profile`, instead
-        // handle profile lookup manually
-        let profile = self.get_profile(target).expect("Could not find target profile");
+    /// Resolves a profile the way `use`/`shell` pick one to switch to: we never want to fall back to a default here
+    /// (unlike `with_profile`), so an exact profile name switches straight away (keeps scripted use-cases working);
+    /// anything else (no argument, or a partial name) opens the interactive picker, seeded with whatever was typed.
+    fn resolve_switch_target(&self, target: Option<String>) -> Option<&Profile> {
+        let exact_match = target.as_ref().and_then(|t| self.get_profile(t.to_owned()));
+
+        match exact_match {
+            Some(profile) => Some(profile),
+            None => {
+                let query = target.as_deref().unwrap_or("");
+                self.pick_profile_interactively(query)
+            }
+        }
+    }
+
+    /// Applies `profile`'s full identity via the backend: user.name/user.email, plus any
+    /// extra config keys (signing key, per-host SSH command, ...) the profile carries.
+    fn apply_profile(&self, profile: &Profile) -> Result<(), backend::BackendError> {
+        self.backend.set_identity(profile.author.as_ref(), profile.email.as_ref())?;
+
+        if let Some(config) = &profile.config {
+            for (key, value) in config {
+                self.backend.set_config(key, value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Switches to `target`'s identity, returning the process exit code: `0` on success,
+    /// or a POSIX-ish code derived from whatever the backend failed with (e.g. the repo
+    /// is read-only, the config key is locked, or the backend is missing entirely).
+    fn handle_use(&self, target: Option<String>) -> i32 {
+        let profile = match self.resolve_switch_target(target) {
+            Some(profile) => profile,
+            None => {
+                println!("No profile selected");
+                return 1;
+            }
+        };
+
+        match self.apply_profile(profile) {
+            Ok(()) => 0,
+            Err(e) => {
+                println!("Couldn't switch to profile '{}': {}", profile.name, e);
+                e.exit_code()
+            }
+        }
+    }
+
+    /// Spawns a subshell with `target`'s identity, returning the process exit code:
+    /// the subshell's own exit code, or `1` if no profile was selected.
+    fn handle_shell(&self, target: Option<String>) -> i32 {
+        let profile = match self.resolve_switch_target(target) {
+            Some(profile) => profile,
+            None => {
+                println!("No profile selected");
+                return 1;
+            }
+        };
+
+        let shell = env::var("SHELL").unwrap_or_else(|_| default_shell().to_owned());
+
+        println!("Spawning {} with profile '{}' ({} <{}>)", shell, profile.name, profile.author, profile.email);
+
+        let result = Command::new(&shell)
+                        .env("GIT_AUTHOR_NAME", &profile.author)
+                        .env("GIT_AUTHOR_EMAIL", &profile.email)
+                        .env("GIT_COMMITTER_NAME", &profile.author)
+                        .env("GIT_COMMITTER_EMAIL", &profile.email)
+                        .status()
+                        .expect("failed to spawn subshell");
 
-        // TODO: These have results we should probably pay attention to
-        git_command(vec!["config", "user.name", profile.author.as_ref()]);
-        git_command(vec!["config", "user.email", profile.email.as_ref()]);
+        result.code().unwrap_or(1)
     }
 
     fn handle_url(&self, profile_name: Option<&str>, project_name: String) {
@@ -349,17 +490,108 @@ impl GitProfilesApp<'_> {
         self.with_profile(profile_name, |p| println!("{} <{}>", p.author, p.email));
     }
 
-    fn handle_new(&self, profile_name: &str, author_name: &str, author_email: &str, username: Option<&str>, 
-                    remote: Option<&str>) 
+    /// Clones `target` (a full URL, or a bare project name to expand via the profile's URL
+    /// template) and immediately applies the chosen profile's identity in the fresh checkout.
+    /// Returns the process exit code: `0` on success, or a POSIX-ish code derived from
+    /// whatever the backend failed with.
+    fn handle_clone(&self, profile_name: Option<&str>, target: String) -> i32 {
+        let profile = match self.resolve_profile(profile_name) {
+            Some(profile) => profile,
+            None => {
+                println!("Couldn't find specified profile, or work out a default");
+                return 1;
+            }
+        };
+
+        let url = if is_clone_url(&target) {
+            target.clone()
+        } else {
+            let urlspec = match &profile.url {
+                Some(url) => url.as_ref(),
+                None => "git@github.com:{{username}}/{{project}}"
+            };
+
+            let template = Template::new(urlspec).expect("Failed to create template from urlspec");
+            template.render(&profile.render_data(target.clone()))
+        };
+
+        if let Err(e) = self.backend.clone_repo(&url) {
+            println!("Couldn't clone {}: {}", url, e);
+            return e.exit_code();
+        }
+
+        let dest = clone_destination_dir(&url);
+        if env::set_current_dir(&dest).is_err() {
+            println!("Cloned, but couldn't find '{}' to apply profile '{}'", dest, profile.name);
+            return 1;
+        }
+
+        match self.apply_profile(profile) {
+            Ok(()) => {
+                println!("Cloned {} and applied profile '{}'", url, profile.name);
+                0
+            },
+            Err(e) => {
+                println!("Cloned, but couldn't apply profile '{}': {}", profile.name, e);
+                e.exit_code()
+            }
+        }
+    }
+
+    // This mirrors the `new` subcommand's arg list one-for-one; a builder would just move the
+    // same sprawl into a second type without making any individual call site clearer.
+    #[allow(clippy::too_many_arguments)]
+    fn handle_new(&self, profile_name: &str, author_name: Option<&str>, author_email: Option<&str>,
+                    username: Option<&str>, remote: Option<&str>, from_github: Option<&str>, set_values: Vec<&str>)
     {
-        let mut profile = Profile::new(profile_name, author_name, author_email);
+        let mut author_name = author_name.map(|s| s.to_owned());
+        let mut author_email = author_email.map(|s| s.to_owned());
+        let mut username = username.map(|s| s.to_owned());
+        let mut remote = remote.map(|s| s.to_owned());
+
+        if let Some(handle) = from_github {
+            match github::fetch_identity(handle) {
+                Ok(identity) => {
+                    author_name = author_name.or(identity.author);
+                    author_email = author_email.or(identity.email);
+                    username = username.or(Some(identity.username));
+                    remote = remote.or(identity.url);
+                },
+                Err(e) => println!("Couldn't fetch GitHub profile for {}, falling back to supplied args: {}", handle, e),
+            }
+        }
+
+        let author_name = match author_name {
+            Some(name) => name,
+            None => {
+                println!("Author name required: pass AUTHOR, or use --from-github on a handle with a public name");
+                return;
+            }
+        };
+
+        let author_email = match author_email {
+            Some(email) => email,
+            None => {
+                println!("Author email required: pass EMAIL, or use --from-github on a handle with a public email");
+                return;
+            }
+        };
+
+        let mut profile = Profile::new(profile_name, &author_name, &author_email);
 
         if let Some(user) = username {
-            profile.with_username(user);
+            profile.with_username(&user);
         }
 
         if let Some(url) = remote {
-            profile.with_remote_url(url);
+            profile.with_remote_url(&url);
+        }
+
+        for set_value in set_values {
+            match set_value.split_once('=') {
+                Some((key, value)) => { profile.with_config_value(key, value); },
+                None => println!("Ignoring malformed --set '{}', expected key=value", set_value),
+            }
         }
 
         let mut new_profiles = Vec::new();
@@ -399,52 +631,132 @@ impl GitProfilesApp<'_> {
     }
 }
 
-fn git_command(args: Vec<&str>) -> String {
-    let mut command = Command::new("git");
+/// Fallback shell for `shell` subcommand when `$SHELL` isn't set.
+#[cfg(windows)]
+fn default_shell() -> &'static str {
+    "cmd.exe"
+}
 
-    for arg in args {
-        command.arg(arg);
-    }
+#[cfg(not(windows))]
+fn default_shell() -> &'static str {
+    "/bin/sh"
+}
 
-    let output_streams = command.output().expect("failed to execute process");
-    let output = from_utf8(&output_streams.stdout).unwrap().trim_end();
+/// Whether `target` already looks like a clone URL, as opposed to a bare project name
+/// that should be expanded via the profile's URL template.
+fn is_clone_url(target: &str) -> bool {
+    target.contains("://") || target.contains('@') || target.starts_with('.') || target.starts_with('/')
+}
 
-    return output.to_owned();
+/// Works out the directory `git clone <url>` will check the repo out into, mirroring
+/// git's own "last path segment, minus a trailing .git" rule.
+fn clone_destination_dir(url: &str) -> String {
+    let trimmed = url.trim_end_matches('/');
+    let last = trimmed.rsplit(['/', ':']).next().unwrap_or(trimmed);
+    last.trim_end_matches(".git").to_owned()
 }
 
 fn main() {
     let app = GitProfilesApp::new().expect("profile loading failed, check your profile config");
 
-    if let Some(args) = &app.args {
+    let exit_code = if let Some(args) = &app.args {
         match args.subcommand() {
-            ("list", _) => app.handle_list(),
+            ("list", _) => { app.handle_list(); 0 },
             ("new", Some(sub_matches)) => {
                 let profile_name = sub_matches.value_of("PROFILE").expect("failed to parse profile name");
-                let author_name = sub_matches.value_of("AUTHOR").expect("failed to parse author name");
-                let author_email = sub_matches.value_of("EMAIL").expect("failed to parse author email");
+                let author_name = sub_matches.value_of("AUTHOR");
+                let author_email = sub_matches.value_of("EMAIL");
                 let url = sub_matches.value_of("URL");
-                let username = sub_matches.value_of("USER");
+                let username = sub_matches.value_of("USERNAME");
+                let from_github = sub_matches.value_of("FROM_GITHUB");
+                let set_values: Vec<&str> = sub_matches.values_of("SET").map(|v| v.collect()).unwrap_or_default();
 
-                app.handle_new(profile_name, author_name, author_email, username, url);
+                app.handle_new(profile_name, author_name, author_email, username, url, from_github, set_values);
+                0
             },
             ("use", Some(sub_matches)) => {
-                let profile_name = sub_matches.value_of("PROFILE").expect("failed to parse profile name");
-                app.handle_use(profile_name.to_owned());
+                let profile_name = sub_matches.value_of("PROFILE").map(|s| s.to_owned());
+                app.handle_use(profile_name)
+            },
+            ("shell", Some(sub_matches)) => {
+                let profile_name = sub_matches.value_of("PROFILE").map(|s| s.to_owned());
+                app.handle_shell(profile_name)
             },
             ("url", Some(sub_matches)) => {
                 let project_name = sub_matches.value_of("PROJECT").expect("failed to parse project name");
                 let profile_name = sub_matches.value_of("PROFILE");
                 app.handle_url(profile_name, project_name.to_owned());
+                0
+            },
+            ("clone", Some(sub_matches)) => {
+                let target = sub_matches.value_of("TARGET").expect("failed to parse clone target");
+                let profile_name = sub_matches.value_of("PROFILE");
+                app.handle_clone(profile_name, target.to_owned())
             },
             ("author", Some(sub_matches)) => {
                 let profile_name = sub_matches.value_of("PROFILE");
                 app.handle_author(profile_name);
+                0
             },
             ("edit", Some(sub_matches)) => {
                 let editor = sub_matches.value_of("EDITOR");
                 app.handle_edit(editor);
+                0
             },
-            _ => println!("{}", args.usage()), // TODO: Should list sub-commands
-        };
+            _ => { println!("{}", args.usage()); 0 }, // TODO: Should list sub-commands
+        }
+    } else {
+        0
+    };
+
+    process::exit(exit_code);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockBackend {
+        is_repo: bool,
+        email: Option<String>,
+    }
+
+    impl Backend for MockBackend {
+        fn set_identity(&self, _author: &str, _email: &str) -> Result<(), backend::BackendError> { Ok(()) }
+        fn set_config(&self, _key: &str, _value: &str) -> Result<(), backend::BackendError> { Ok(()) }
+        fn current_email(&self) -> Result<Option<String>, backend::BackendError> { Ok(self.email.clone()) }
+        fn is_repo(&self) -> bool { self.is_repo }
+        fn clone_repo(&self, _url: &str) -> Result<(), backend::BackendError> { Ok(()) }
+    }
+
+    fn app_with<'a>(backend: MockBackend, profiles: Vec<Profile>) -> GitProfilesApp<'a> {
+        GitProfilesApp { profiles: Some(profiles), args: None, backend: Box::new(backend) }
+    }
+
+    // get_profile_in_local_use previously called `self.backend.current_email()?` directly
+    // in a function returning `Option<&Profile>`, which doesn't compile (`?` can't convert
+    // a `Result` into an `Option`). That left the tree non-building for two commits before
+    // an unrelated change incidentally fixed it - this covers the behavior so a future
+    // regression here fails a test instead of just failing to compile unnoticed.
+    #[test]
+    fn local_use_resolves_profile_by_current_email() {
+        let app = app_with(
+            MockBackend { is_repo: true, email: Some("a@example.com".to_owned()) },
+            vec![Profile::new("work", "A", "a@example.com")],
+        );
+
+        assert_eq!(app.get_profile_in_local_use().map(|p| p.name.as_str()), Some("work"));
+    }
+
+    #[test]
+    fn local_use_is_none_outside_a_repo() {
+        let app = app_with(MockBackend { is_repo: false, email: Some("a@example.com".to_owned()) }, vec![]);
+        assert!(app.get_profile_in_local_use().is_none());
+    }
+
+    #[test]
+    fn local_use_is_none_when_no_identity_is_set() {
+        let app = app_with(MockBackend { is_repo: true, email: None }, vec![]);
+        assert!(app.get_profile_in_local_use().is_none());
     }
 }
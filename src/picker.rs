@@ -0,0 +1,78 @@
+extern crate crossterm;
+
+use std::io::{stdout, Write};
+
+use crossterm::event::{self, Event, KeyCode};
+use crossterm::terminal;
+
+use crate::fuzzy;
+
+/// Runs an interactive fuzzy picker over `candidates`, seeded with
+/// `initial_query`. The list narrows as the user types, Up/Down move the
+/// selection, Enter confirms. Returns `None` if the user cancelled with Esc.
+pub fn pick(candidates: &[&str], initial_query: &str) -> Result<Option<String>, std::io::Error> {
+    let mut query = initial_query.to_owned();
+    let mut selected = 0usize;
+    let mut out = stdout();
+
+    terminal::enable_raw_mode()?;
+    let outcome = run(&mut out, candidates, &mut query, &mut selected);
+    terminal::disable_raw_mode()?;
+
+    // Leave the picker's own lines behind rather than fighting the shell for control
+    // of the rest of the screen.
+    write!(out, "\r\n")?;
+
+    outcome
+}
+
+fn run(out: &mut impl Write, candidates: &[&str], query: &mut String, selected: &mut usize)
+    -> Result<Option<String>, std::io::Error>
+{
+    let mut last_rendered_lines = 0usize;
+
+    loop {
+        let ranked = fuzzy::rank(query, candidates);
+        if *selected >= ranked.len() {
+            *selected = ranked.len().saturating_sub(1);
+        }
+
+        last_rendered_lines = render(out, query, &ranked, *selected, last_rendered_lines)?;
+
+        if let Event::Key(key) = event::read()? {
+            match key.code {
+                KeyCode::Enter => return Ok(ranked.get(*selected).map(|s| s.to_string())),
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Up => *selected = selected.saturating_sub(1),
+                KeyCode::Down => *selected = (*selected + 1).min(ranked.len().saturating_sub(1)),
+                KeyCode::Backspace => { query.pop(); *selected = 0; },
+                KeyCode::Char(c) => { query.push(c); *selected = 0; },
+                _ => {}
+            }
+        }
+    }
+}
+
+fn render(out: &mut impl Write, query: &str, ranked: &[&str], selected: usize, previous_lines: usize)
+    -> Result<usize, std::io::Error>
+{
+    for _ in 0..previous_lines {
+        write!(out, "\r\x1b[K\x1b[A")?;
+    }
+    write!(out, "\r\x1b[K")?;
+
+    write!(out, "Switch to profile: {}\r\n", query)?;
+
+    for (i, name) in ranked.iter().enumerate() {
+        write!(out, "\x1b[K")?;
+        if i == selected {
+            write!(out, "> {}\r\n", name)?;
+        } else {
+            write!(out, "  {}\r\n", name)?;
+        }
+    }
+
+    out.flush()?;
+    // +1 for the header line above the list, so the next render's erase loop clears it too.
+    Ok(ranked.len() + 1)
+}